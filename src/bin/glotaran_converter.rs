@@ -0,0 +1,142 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use glotaran_converter_lib::{
+    run_batch, run_das6, run_lfp, run_r4, ConversionOptions, OutputFormat, Sink, Source,
+};
+
+/// Convert Edinburgh Instruments LFP, Horiba DataStation (DAS6) and R4 exports
+/// into Glotaran-compatible `.ascii` files.
+#[derive(Parser)]
+#[command(name = "glotaran-converter", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+
+    /// Name written into the `.ascii` file's author line
+    #[arg(long, global = true, default_value = "Eduardo Gonik")]
+    author: String,
+
+    /// Glotaran layout to emit
+    #[arg(long, global = true, value_enum, default_value_t = FormatArg::WavelengthExplicit)]
+    format: FormatArg,
+
+    /// Drop rows before this time (requires --time-max)
+    #[arg(long, global = true, requires = "time_max")]
+    time_min: Option<f32>,
+    /// Drop rows after this time (requires --time-min)
+    #[arg(long, global = true, requires = "time_min")]
+    time_max: Option<f32>,
+
+    /// Drop columns below this wavelength (requires --wavelength-max)
+    #[arg(long, global = true, requires = "wavelength_max")]
+    wavelength_min: Option<f32>,
+    /// Drop columns above this wavelength (requires --wavelength-min)
+    #[arg(long, global = true, requires = "wavelength_min")]
+    wavelength_max: Option<f32>,
+}
+
+impl Cli {
+    fn options(&self) -> ConversionOptions {
+        ConversionOptions {
+            author: self.author.clone(),
+            format: OutputFormat::from(self.format),
+            time_range: self.time_min.zip(self.time_max),
+            wavelength_range: self.wavelength_min.zip(self.wavelength_max),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Convert an Edinburgh Instruments LFP export
+    Lfp {
+        /// Path to the LFP CSV export, `-` or omitted to read piped stdin
+        input: Option<String>,
+        /// Output `.ascii` path, omitted to write to stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Convert a Horiba DataStation (DAS6) export
+    Das6 {
+        /// Path to the DataStation tab-separated export, `-` or omitted to read piped stdin
+        input: Option<String>,
+        /// Time of the sync pulse, in channels
+        #[arg(long, default_value_t = 0f32)]
+        sync_delay: f32,
+        /// Nanoseconds per channel
+        #[arg(long, default_value_t = 2.5e4)]
+        ns_per_chn: f32,
+        /// Output `.ascii` path, omitted to write to stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Convert an R4 export
+    R4 {
+        /// Path to the R4 CSV export, `-` or omitted to read piped stdin
+        input: Option<String>,
+        /// Output `.ascii` path, omitted to write to stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Convert every recognized export in a directory into one `.tar` archive
+    Batch {
+        /// Directory to scan for LFP/DAS6/R4 exports
+        directory: String,
+        /// Output `.tar` path
+        #[arg(long)]
+        output: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FormatArg {
+    WavelengthExplicit,
+    TimeExplicit,
+}
+
+impl From<FormatArg> for OutputFormat {
+    fn from(value: FormatArg) -> Self {
+        match value {
+            FormatArg::WavelengthExplicit => OutputFormat::WavelengthExplicit,
+            FormatArg::TimeExplicit => OutputFormat::TimeExplicit,
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let options = cli.options();
+    let destination = match cli.command {
+        Commands::Lfp { input, output } => {
+            let source = Source::from_arg(input.as_deref())?;
+            let sink = Sink::from_arg(output.as_deref());
+            run_lfp(&source, &sink, &options).map_err(|err| {
+                err.emit();
+                err
+            })?
+        }
+        Commands::Das6 {
+            input,
+            sync_delay,
+            ns_per_chn,
+            output,
+        } => {
+            let source = Source::from_arg(input.as_deref())?;
+            let sink = Sink::from_arg(output.as_deref());
+            run_das6(&source, sync_delay, ns_per_chn, &sink, &options).map_err(|err| {
+                err.emit();
+                err
+            })?
+        }
+        Commands::R4 { input, output } => {
+            let source = Source::from_arg(input.as_deref())?;
+            let sink = Sink::from_arg(output.as_deref());
+            run_r4(&source, &sink, &options).map_err(|err| {
+                err.emit();
+                err
+            })?
+        }
+        Commands::Batch { directory, output } => run_batch(&directory, &options, &output)?,
+    };
+    eprintln!("Wrote {destination}");
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::files::SimpleFiles;
+use codespan_reporting::term::{
+    self,
+    termcolor::{ColorChoice, StandardStream},
+};
+use std::ops::Range;
+
+/// A parse failure anchored to a byte span in the source file it came from,
+/// so it can be rendered with source context (interactive callers) or
+/// summarized as plain text (library callers, see `UnparsableFileError`'s
+/// `Display` impl).
+#[derive(Debug, Clone)]
+pub(crate) struct ParseDiagnostic {
+    message: String,
+    span: Range<usize>,
+    note: String,
+}
+
+impl ParseDiagnostic {
+    pub(crate) fn bad_wavelength_header(span: Range<usize>) -> Self {
+        ParseDiagnostic {
+            message: "header cell isn't a 3-digit wavelength".to_owned(),
+            span,
+            note: "expected a 3-digit wavelength here, e.g. `450`".to_owned(),
+        }
+    }
+
+    pub(crate) fn non_numeric_cell(span: Range<usize>, value: &str) -> Self {
+        ParseDiagnostic {
+            message: format!("expected a number, found `{value}`"),
+            span,
+            note: "this cell should hold a signal value".to_owned(),
+        }
+    }
+
+    pub(crate) fn column_count_mismatch(
+        span: Range<usize>,
+        header_columns: usize,
+        row_columns: usize,
+    ) -> Self {
+        ParseDiagnostic {
+            message: format!("this row has {row_columns} columns but the header has {header_columns}"),
+            span,
+            note: "every row must have the same number of columns as the header".to_owned(),
+        }
+    }
+
+    pub(crate) fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Pretty-print this diagnostic to stderr with source context.
+    pub(crate) fn emit(&self, filename: &str, source: &str) {
+        let mut files = SimpleFiles::new();
+        let file_id = files.add(filename, source);
+        let diagnostic = Diagnostic::error()
+            .with_message(&self.message)
+            .with_labels(vec![Label::primary(file_id, self.span.clone())])
+            .with_notes(vec![self.note.clone()]);
+        let writer = StandardStream::stderr(ColorChoice::Auto);
+        let config = term::Config::default();
+        let _ = term::emit(&mut writer.lock(), &config, &files, &diagnostic);
+    }
+}
+
+/// Byte span of the line starting at `byte_start`, for labeling a whole
+/// record when we don't have a more precise per-cell offset.
+pub(crate) fn line_span(source: &str, byte_start: usize) -> Range<usize> {
+    let end = source[byte_start..]
+        .find('\n')
+        .map(|offset| byte_start + offset)
+        .unwrap_or(source.len());
+    byte_start..end
+}
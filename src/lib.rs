@@ -1,9 +1,154 @@
+mod diagnostics;
+
 use anyhow::Context;
+use diagnostics::ParseDiagnostic;
 use regex::Regex;
-use std::{error::Error, fmt::Display, fs::OpenOptions, io::Write, path::Path};
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::OpenOptions,
+    io::{IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Where a converter reads its input from: a real file, or stdin (selected
+/// with `-` or by piping into the process without an input path).
+#[derive(Debug, Clone)]
+pub enum Source {
+    Path(PathBuf),
+    Stdin,
+}
+
+impl Source {
+    /// `Some("-")` and `None` (when stdin isn't a terminal, i.e. it's piped)
+    /// both mean "read from stdin"; anything else is a file path.
+    pub fn from_arg(arg: Option<&str>) -> anyhow::Result<Source> {
+        match arg {
+            Some("-") => Ok(Source::Stdin),
+            Some(path) => Ok(Source::Path(PathBuf::from(path))),
+            None if !std::io::stdin().is_terminal() => Ok(Source::Stdin),
+            None => anyhow::bail!("no input path given and stdin isn't piped"),
+        }
+    }
+
+    fn read_to_string(&self) -> std::io::Result<String> {
+        match self {
+            Source::Path(path) => std::fs::read_to_string(path),
+            Source::Stdin => {
+                let mut buffer = String::new();
+                std::io::stdin().read_to_string(&mut buffer)?;
+                Ok(buffer)
+            }
+        }
+    }
+
+    /// Human-readable name for diagnostics.
+    fn display_name(&self) -> String {
+        match self {
+            Source::Path(path) => path.display().to_string(),
+            Source::Stdin => "<stdin>".to_owned(),
+        }
+    }
+}
+
+/// Where a converter writes its output to: a real file (truncated, so
+/// re-running is idempotent), or stdout (selected by omitting `--output`).
+#[derive(Debug, Clone)]
+pub enum Sink {
+    Path(PathBuf),
+    Stdout,
+}
+
+impl Sink {
+    pub fn from_arg(arg: Option<&str>) -> Sink {
+        match arg {
+            Some(path) => Sink::Path(PathBuf::from(path)),
+            None => Sink::Stdout,
+        }
+    }
+
+    fn write_all(&self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Sink::Path(path) => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path)?;
+                file.write_all(data)
+            }
+            Sink::Stdout => std::io::stdout().write_all(data),
+        }
+    }
+
+    /// Human-readable name for the `.ascii` title line.
+    fn label(&self) -> String {
+        match self {
+            Sink::Path(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+            Sink::Stdout => "-".to_owned(),
+        }
+    }
+
+    /// Human-readable description of where output went, for user-facing messages.
+    fn describe(&self) -> String {
+        match self {
+            Sink::Path(path) => path.display().to_string(),
+            Sink::Stdout => "stdout".to_owned(),
+        }
+    }
+}
+
+/// Layout `write_to_file` emits the converted data in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    WavelengthExplicit,
+    TimeExplicit,
+}
+
+/// Rectangular view of a converted trace, independent of which instrument it
+/// came from. `wavelengths` are the column headers; each `rows` entry is
+/// `[time, signal_at_wavelengths[0], signal_at_wavelengths[1], ...]`.
+#[derive(Debug, Clone)]
+pub struct DataMatrix {
+    pub wavelengths: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Bundles the knobs shared by every `run_*` converter, so adding one doesn't
+/// keep growing each function's argument list.
+#[derive(Debug, Clone)]
+pub struct ConversionOptions {
+    pub author: String,
+    pub format: OutputFormat,
+    /// Keep only rows whose time falls in `[min, max]`.
+    pub time_range: Option<(f32, f32)>,
+    /// Keep only columns whose wavelength falls in `[min, max]`.
+    pub wavelength_range: Option<(f32, f32)>,
+}
+
+impl Default for ConversionOptions {
+    fn default() -> Self {
+        ConversionOptions {
+            author: "Eduardo Gonik".to_owned(),
+            format: OutputFormat::WavelengthExplicit,
+            time_range: None,
+            wavelength_range: None,
+        }
+    }
+}
+
+/// A file failed to parse. `Display` always gives a plain-text summary for
+/// library callers that have no terminal to draw on; callers that do (the
+/// CLI) can call `emit` for a source-annotated `codespan-reporting` report.
 #[derive(Debug, Clone)]
 pub struct UnparsableFileError {
     inner: String,
+    // Boxed because ParseDiagnostic plus its filename/source strings would
+    // otherwise make every `Result<_, UnparsableFileError>` oversized (clippy::result_large_err).
+    diagnostic: Option<Box<(ParseDiagnostic, String, String)>>, // (diagnostic, filename, source)
 }
 
 impl Error for UnparsableFileError {}
@@ -16,84 +161,172 @@ impl From<csv::Error> for UnparsableFileError {
     fn from(value: csv::Error) -> Self {
         UnparsableFileError {
             inner: value.to_string(),
+            diagnostic: None,
+        }
+    }
+}
+impl From<std::io::Error> for UnparsableFileError {
+    fn from(value: std::io::Error) -> Self {
+        UnparsableFileError {
+            inner: value.to_string(),
+            diagnostic: None,
+        }
+    }
+}
+
+impl UnparsableFileError {
+    fn from_diagnostic(filename: &str, source: &str, diagnostic: ParseDiagnostic) -> Self {
+        UnparsableFileError {
+            inner: diagnostic.message().to_owned(),
+            diagnostic: Some(Box::new((diagnostic, filename.to_owned(), source.to_owned()))),
+        }
+    }
+
+    /// Folds an `anyhow::Error` (e.g. from `render`/`write_to_file`) back into
+    /// this type, preserving the existing `From<io::Error>` conversion for the
+    /// broken-pipe/missing-directory case so write failures stay recoverable
+    /// instead of forcing callers to `.unwrap()`.
+    fn from_anyhow(err: anyhow::Error) -> Self {
+        match err.downcast::<std::io::Error>() {
+            Ok(io_err) => UnparsableFileError::from(io_err),
+            Err(err) => UnparsableFileError {
+                inner: err.to_string(),
+                diagnostic: None,
+            },
+        }
+    }
+
+    /// Pretty-print this error with source context to stderr, if it carries
+    /// one; falls back to doing nothing so callers can always call it and
+    /// rely on `Display`/`{}` for the plain-text message.
+    pub fn emit(&self) {
+        if let Some(boxed) = &self.diagnostic {
+            let (diagnostic, filename, source) = boxed.as_ref();
+            diagnostic.emit(filename, source);
         }
     }
 }
 
 /// Takes in a Edinburgh Instrument LFP file and returns a glotaran compatible
 /// .ascii file in the `wavelength explicit` format
+/// by default (`options.format` selects `time explicit` instead)
+/// ```no_run
+/// use glotaran_converter_lib::{run_lfp, ConversionOptions, Source, Sink};
+/// let source = Source::from_arg(Some("example_lfp.txt")).unwrap(); // Valid EI file
+/// let sink = Sink::from_arg(Some("example_lfp.ascii"));
+/// let destination = run_lfp(&source, &sink, &ConversionOptions::default()).expect("Error converting/reading file");
+/// assert_eq!(destination, "example_lfp.ascii");
 /// ```
-/// use glotaran_converter_lib::run_lfp;
-/// let filename : &str = "example_lfp.txt"; // Valid EI file
-/// let new_filename = run_lfp(filename).expect("Error converting/reading file");
-/// let prefix_a = format!("{}",filename.split_once(".").unwrap().0);
-/// let prefix_b = format!("{}",new_filename.split_once(".").unwrap().0);
-/// assert_eq!(prefix_a, prefix_b);
-/// ```
-pub fn run_lfp(source: &str) -> anyhow::Result<String> {
-    let output_filename = {
-        let path = Path::new(source);
-        let ext = path.with_extension("ascii");
-        let filename = ext.file_name();
-        filename.unwrap().to_str().unwrap().to_owned()
-    };
+pub fn run_lfp(source: &Source, sink: &Sink, options: &ConversionOptions) -> Result<String, UnparsableFileError> {
+    let matrix = crop(
+        parse_lfp(source)?,
+        options.time_range,
+        options.wavelength_range,
+    );
+    write_to_file(matrix, sink, &options.author, options.format).map_err(UnparsableFileError::from_anyhow)?;
+    Ok(sink.describe())
+}
+
+fn parse_lfp(source: &Source) -> Result<DataMatrix, UnparsableFileError> {
+    let contents = source.read_to_string()?;
     let re = Regex::new(r"(\d){3}").unwrap();
     let mut rdr = csv::ReaderBuilder::new()
         .delimiter(b',')
         .flexible(true)
-        .from_path(source)
-        .context("Source file could not be read")?;
+        .from_reader(contents.as_bytes());
     let headers_raw = rdr.records().next().unwrap()?;
-    let headers = headers_raw
-        .into_iter()
-        .map(|col| match re.find(col) {
-            Some(mtch) => mtch.as_str(),
-            None => "",
-        })
-        .collect::<Vec<_>>();
-    let mut body: Vec<Vec<String>> = vec![];
+    let header_span = diagnostics::line_span(
+        &contents,
+        headers_raw.position().map_or(0, |p| p.byte() as usize),
+    );
+    let mut wavelengths = Vec::with_capacity(headers_raw.len().saturating_sub(1));
+    for col in headers_raw.iter().skip(1) {
+        // la primer columna es la de los tiempos, no tiene longitud de onda
+        match re.find(col) {
+            Some(mtch) => wavelengths.push(mtch.as_str().to_owned()),
+            None => {
+                return Err(UnparsableFileError::from_diagnostic(
+                    &source.display_name(),
+                    &contents,
+                    ParseDiagnostic::bad_wavelength_header(header_span),
+                ))
+            }
+        }
+    }
+    let mut rows: Vec<Vec<String>> = vec![];
     for record in rdr.records().skip(8) {
         let record_vec = record?.into_iter().map(|s| s.to_owned()).collect();
-        body.push(record_vec)
+        rows.push(record_vec)
     }
-    let headlines = headers.len() - 1; // -1 porque se agrega una columna vacia donde están los tiempos
-    println!(
-        "LFP R4 Headers len directo = {} y como reportado {}",
-        headers.len(),
-        headlines
-    );
-    let filename = write_to_file(headers, body, headlines, &output_filename)
-        .context("Output file couldn't be written")?;
-    return anyhow::Ok(filename);
+    Ok(DataMatrix { wavelengths, rows })
 }
 
 /// Takes in a Horiba DataStation text file (generated in datastation software, copying all traces to clipboard) and returns a glotaran compatible
 /// .ascii file in the `wavelength explicit` format
-/// ```
-/// use glotaran_converter_lib::run_das6;
-/// let filename : &str = "example_trp.txt"; // Valid DataStation file
-/// let output_filename : String = "example_trp.ascii".to_owned();
+/// by default (`options.format` selects `time explicit` instead)
+/// ```no_run
+/// use glotaran_converter_lib::{run_das6, ConversionOptions, Source, Sink};
+/// let source = Source::from_arg(Some("example_trp.txt")).unwrap(); // Valid DataStation file
+/// let sink = Sink::from_arg(Some("example_trp.ascii"));
 /// let sync_delay : f32 = 0f32;
 /// let ns_per_chn : f32 = 2.5e4;
-/// let new_filename = run_das6(filename, sync_delay, ns_per_chn,output_filename).expect("Error converting/reading file");
-/// let prefix_a = format!("{}",filename.split_once(".").unwrap().0);
-/// let prefix_b = format!("{}",new_filename.split_once(".").unwrap().0);
-/// assert_eq!(prefix_a, prefix_b);
+/// let destination = run_das6(&source, sync_delay, ns_per_chn, &sink, &ConversionOptions::default()).expect("Error converting/reading file");
+/// assert_eq!(destination, "example_trp.ascii");
 /// ```
 pub fn run_das6(
-    source: &str,
+    source: &Source,
     sync_delay: f32,
     ns_per_chn: f32,
-    output_filename: String,
+    sink: &Sink,
+    options: &ConversionOptions,
 ) -> Result<String, UnparsableFileError> {
+    let matrix = crop(
+        parse_das6(source, sync_delay, ns_per_chn)?,
+        options.time_range,
+        options.wavelength_range,
+    );
+    write_to_file(matrix, sink, &options.author, options.format).map_err(UnparsableFileError::from_anyhow)?;
+    Ok(sink.describe())
+}
+
+fn parse_das6(source: &Source, sync_delay: f32, ns_per_chn: f32) -> Result<DataMatrix, UnparsableFileError> {
+    let contents = source.read_to_string()?;
     let mut rdr = csv::ReaderBuilder::new()
         .delimiter(b'\t')
-        .from_path(source)
-        .expect("Problema leyendo archivo");
+        .from_reader(contents.as_bytes());
+    let header_record = rdr.headers()?.clone();
+    let header_count = header_record.len();
     let re = Regex::new(r"(\d){3}").unwrap();
-    let mut body: Vec<Vec<_>> = vec![];
+    let mut rows: Vec<Vec<_>> = vec![];
     for (n, record) in rdr.records().enumerate() {
-        let mut line = record?
+        let record = record?;
+        if record.len() != header_count {
+            let span = diagnostics::line_span(
+                &contents,
+                record.position().map_or(0, |p| p.byte() as usize),
+            );
+            return Err(UnparsableFileError::from_diagnostic(
+                &source.display_name(),
+                &contents,
+                ParseDiagnostic::column_count_mismatch(span, header_count, record.len()),
+            ));
+        }
+        // Column 0 is the prompt/label column `line.remove(1)` drops below, so
+        // it's never emitted and doesn't need to be numeric.
+        for cell in record.iter().skip(1) {
+            if cell.parse::<f32>().is_err() {
+                let span = diagnostics::line_span(
+                    &contents,
+                    record.position().map_or(0, |p| p.byte() as usize),
+                );
+                return Err(UnparsableFileError::from_diagnostic(
+                    &source.display_name(),
+                    &contents,
+                    ParseDiagnostic::non_numeric_cell(span, cell),
+                ));
+            }
+        }
+        let mut line = record
             .into_iter()
             .map(|recn| recn.to_string())
             .collect::<Vec<String>>();
@@ -102,125 +335,331 @@ pub fn run_das6(
             format!("{}", ((n as f32 - sync_delay) * ns_per_chn) as i32),
         );
         line.remove(1); // Drop prompt
-        body.push(line.clone()); // desclonar later
+        rows.push(line);
     }
-    let mut headers = rdr
-        .headers()?
+    let wavelengths = header_record
         .into_iter()
         .filter_map(|rec| match re.captures(rec) {
             None => None,
             Some(caps) => caps.get(0).map_or(Some("0"), |m| Some(m.as_str())),
         })
-        .collect::<Vec<&str>>();
-    headers.push("");
-    let headlines = headers.len();
-    let filename = write_to_file(headers, body, headlines, &output_filename).unwrap();
-    return Ok(filename);
-}
-
-pub fn run_r4(filename: String) -> anyhow::Result<String> {
-    let output_filename = {
-        let path = Path::new(&filename);
-        let ext = path.with_extension("ascii");
-        let filename = ext.file_name();
-        filename.unwrap().to_str().unwrap().to_owned()
-    };
+        .map(|wavelength| wavelength.to_owned())
+        .collect::<Vec<String>>();
+    Ok(DataMatrix { wavelengths, rows })
+}
+
+pub fn run_r4(source: &Source, sink: &Sink, options: &ConversionOptions) -> Result<String, UnparsableFileError> {
+    let matrix = crop(
+        parse_r4(source)?,
+        options.time_range,
+        options.wavelength_range,
+    );
+    write_to_file(matrix, sink, &options.author, options.format).map_err(UnparsableFileError::from_anyhow)?;
+    Ok(sink.describe())
+}
+
+fn parse_r4(source: &Source) -> Result<DataMatrix, UnparsableFileError> {
+    let contents = source.read_to_string()?;
     let mut reader = csv::ReaderBuilder::new()
         .has_headers(true)
-        .from_path(filename)
-        .context("Couldn't open lfp file")?;
-    let mut document: Vec<_> = reader
-        .headers()
-        .context("Couldn't read headers")?
-        .iter()
-        .map(|record| vec![record.to_owned()])
-        .collect();
-    reader.into_records().for_each(|record| {
-        for (index, cell) in record.unwrap().iter().enumerate() {
-            document.get_mut(index).unwrap().push(cell.to_owned());
+        .from_reader(contents.as_bytes());
+    let header_record = reader.headers()?.clone();
+    let header_count = header_record.len();
+    let header_span = diagnostics::line_span(
+        &contents,
+        header_record.position().map_or(0, |p| p.byte() as usize),
+    );
+    let mut document: Vec<Vec<String>> = header_record.iter().map(|cell| vec![cell.to_owned()]).collect();
+    for record in reader.into_records() {
+        let record = record?;
+        if record.len() != header_count {
+            let span = diagnostics::line_span(
+                &contents,
+                record.position().map_or(0, |p| p.byte() as usize),
+            );
+            return Err(UnparsableFileError::from_diagnostic(
+                &source.display_name(),
+                &contents,
+                ParseDiagnostic::column_count_mismatch(span, header_count, record.len()),
+            ));
         }
-    });
-    document = document
-        .into_iter()
-        .filter(|col| !col.first().unwrap().is_empty())
-        .collect::<Vec<_>>();
-    document.sort_by_key(|row| {
-        let key = {
-            let tmp = row.first().unwrap();
-            if tmp.eq("t") {
-                0
-            } else {
-                tmp.parse::<i32>().expect("Couldn't parse wavelength")
-            }
+        for (index, cell) in record.iter().enumerate() {
+            document[index].push(cell.to_owned());
+        }
+    }
+    document.retain(|col| col.first().is_some_and(|header| !header.is_empty()));
+
+    // Pair each column with its sort key up front (rather than in
+    // `sort_by_key`, which can't propagate a parse failure), so a
+    // non-numeric, non-"t" header becomes a diagnostic instead of a panic.
+    let mut keyed_columns: Vec<(i32, Vec<String>)> = Vec::with_capacity(document.len());
+    for column in document {
+        let header = column.first().expect("retained columns always carry their header cell");
+        let key = if header == "t" {
+            0
+        } else {
+            header.parse::<i32>().map_err(|_| {
+                UnparsableFileError::from_diagnostic(
+                    &source.display_name(),
+                    &contents,
+                    ParseDiagnostic::bad_wavelength_header(header_span.clone()),
+                )
+            })?
         };
-        key
-    });
-    let mut return_headers = vec![];
-    let col_length = return_headers.len();
-    println!(
-        "Longitud de headers {col_length} en el vector final, {}",
-        document.len()
-    );
-    let mut return_body = vec![];
-    for (col_num, column) in document.iter().enumerate() {
+        keyed_columns.push((key, column));
+    }
+    keyed_columns.sort_by_key(|(key, _)| *key);
+
+    let mut wavelengths = vec![];
+    let mut rows: Vec<Vec<String>> = vec![];
+    for (col_num, (_, column)) in keyed_columns.iter().enumerate() {
         for (row_n, cell) in column.iter().enumerate() {
             if row_n == 0 && col_num == 0 {
-                return_headers.push("   ");
+                // la primer columna (tiempos) no tiene longitud de onda
             } else if row_n == 0 {
-                return_headers.push(cell.as_str());
+                wavelengths.push(cell.to_owned());
             } else if col_num == 0 {
-                return_body.push(vec![cell.to_owned()]);
+                rows.push(vec![cell.to_owned()]);
             } else {
-                return_body
-                    .get_mut(row_n - 1)
-                    .unwrap()
-                    .push(cell.to_owned());
+                rows.get_mut(row_n - 1).unwrap().push(cell.to_owned());
             }
         }
     }
-    let col_length = return_headers.len();
-    println!(
-        "shadow of ... Longitud de headers {col_length} pasados a write to file, {}",
-        document.len()
-    );
-    write_to_file(return_headers, return_body, col_length, &output_filename)
-        .context("Couldn't write to file")?;
-    return anyhow::Ok(output_filename.to_owned());
-}
-
-fn write_to_file(
-    headers: Vec<&str>,
-    body: Vec<Vec<String>>,
-    line_number: usize,
-    output_filename: &str,
-) -> anyhow::Result<String> {
-    println!("Contents converted written to file {output_filename}");
-    let filename = output_filename;
-    let mut file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(filename)?;
-    writeln!(file, "{filename}")?;
-    writeln!(file, "Eduardo Gonik")?;
-    writeln!(file, "wavelength explicit")?;
-    // file.write(format!("interval nr {}", (line_number - 1)).as_bytes())?;
-    println!(
-        "Line number = {} as written {}",
-        line_number,
-        line_number - 1
-    );
-    let ii = format!("intervalnr {}", (line_number - 1));
-    writeln!(file, "{ii}")?;
-    println!("ii = {}", ii);
-    file.flush()?;
-    let file2 = OpenOptions::new().append(true).open(filename)?;
-    let mut writer = csv::WriterBuilder::new()
-        .delimiter(b'\t')
-        .from_writer(file2);
-    // let mut writer = csv::Writer::from_writer(file2);
-    writer.write_record(&headers).unwrap();
-    body.into_iter()
-        .for_each(|v| writer.write_record(&v).unwrap());
+    Ok(DataMatrix { wavelengths, rows })
+}
+
+/// Instrument export kind, used to pick a parser when batch-converting a
+/// whole directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Lfp,
+    Das6,
+    R4,
+}
+
+/// Sniffs which converter a file needs, first by extension and, for the
+/// ambiguous `.txt` case, by the delimiter its first line actually uses.
+fn detect_format(path: &Path) -> Option<SourceFormat> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => Some(SourceFormat::Lfp),
+        Some("dat") => Some(SourceFormat::R4),
+        Some("txt") => {
+            let first_line = std::fs::read_to_string(path).ok()?.lines().next()?.to_owned();
+            if first_line.contains('\t') {
+                Some(SourceFormat::Das6)
+            } else {
+                Some(SourceFormat::Lfp)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Converts every recognized export in `directory` and bundles the results,
+/// plus a manifest of what was found, into a single `.tar` archive at
+/// `archive_path`. Each converted file is rendered in memory and streamed
+/// straight into its archive entry, without touching the filesystem.
+pub fn run_batch(directory: &str, options: &ConversionOptions, archive_path: &str) -> anyhow::Result<String> {
+    let pattern = format!("{}/*", directory.trim_end_matches('/'));
+    let archive_file =
+        std::fs::File::create(archive_path).with_context(|| format!("Couldn't create archive {archive_path}"))?;
+    let mut builder = tar::Builder::new(archive_file);
+    let mut manifest = String::from("name\tformat\trows\tcolumns\n");
+
+    for entry in glob::glob(&pattern).context("Invalid batch directory pattern")? {
+        let path = entry.context("Couldn't read directory entry")?;
+        if !path.is_file() {
+            continue;
+        }
+        let Some(source_format) = detect_format(&path) else {
+            continue;
+        };
+        let entry_source = Source::Path(path.clone());
+        let matrix = match source_format {
+            SourceFormat::Lfp => parse_lfp(&entry_source)?,
+            SourceFormat::Das6 => parse_das6(&entry_source, 0f32, 2.5e4)?,
+            SourceFormat::R4 => parse_r4(&entry_source)?,
+        };
+        let matrix = crop(matrix, options.time_range, options.wavelength_range);
+        let rows = matrix.rows.len();
+        let columns = matrix.wavelengths.len();
+
+        let entry_name = path.with_extension("ascii");
+        let entry_name = entry_name.file_name().unwrap().to_string_lossy().into_owned();
+        let ascii = render(&matrix, &entry_name, &options.author, options.format)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(ascii.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, &entry_name, ascii.as_slice())?;
+
+        manifest.push_str(&format!(
+            "{}\t{:?}\t{rows}\t{columns}\n",
+            path.file_name().unwrap().to_string_lossy(),
+            source_format,
+        ));
+    }
+
+    let mut manifest_header = tar::Header::new_gnu();
+    manifest_header.set_size(manifest.len() as u64);
+    manifest_header.set_mode(0o644);
+    manifest_header.set_cksum();
+    builder.append_data(&mut manifest_header, "manifest.tsv", manifest.as_bytes())?;
+
+    builder.finish().context("Couldn't finalize archive")?;
+    anyhow::Ok(archive_path.to_owned())
+}
+
+/// Drops rows outside `time_range` and columns outside `wavelength_range`
+/// (keeping the time column intact). Cells whose time/wavelength doesn't
+/// parse as a number are kept, since pre-sorted exports occasionally carry a
+/// non-numeric marker column.
+fn crop(
+    matrix: DataMatrix,
+    time_range: Option<(f32, f32)>,
+    wavelength_range: Option<(f32, f32)>,
+) -> DataMatrix {
+    let rows = match time_range {
+        Some((min, max)) => matrix
+            .rows
+            .into_iter()
+            .filter(|row| match row.first().and_then(|time| time.parse::<f32>().ok()) {
+                Some(time) => time >= min && time <= max,
+                None => true,
+            })
+            .collect(),
+        None => matrix.rows,
+    };
+
+    let Some((min, max)) = wavelength_range else {
+        return DataMatrix {
+            wavelengths: matrix.wavelengths,
+            rows,
+        };
+    };
+
+    let keep: Vec<usize> = matrix
+        .wavelengths
+        .iter()
+        .enumerate()
+        .filter(|(_, wavelength)| match wavelength.parse::<f32>().ok() {
+            Some(w) => w >= min && w <= max,
+            None => true,
+        })
+        .map(|(index, _)| index)
+        .collect();
+
+    let wavelengths = keep
+        .iter()
+        .map(|&index| matrix.wavelengths[index].clone())
+        .collect();
+    let rows = rows
+        .into_iter()
+        .map(|row| {
+            let mut cropped = vec![row[0].clone()];
+            cropped.extend(
+                keep.iter()
+                    .map(|&index| row.get(index + 1).cloned().unwrap_or_default()),
+            );
+            cropped
+        })
+        .collect();
+    DataMatrix { wavelengths, rows }
+}
+
+/// Pads every row out to `width` cells (or the widest row, whichever is
+/// larger) with empty strings, so ragged input (the LFP path in particular
+/// can hand back short *or* long rows) can be written out without losing
+/// trailing signal columns, and transposed without panicking on an
+/// out-of-bounds index. The time-explicit transpose emits one row per
+/// wavelength, so cells past the last wavelength are not included there.
+fn pad_rows(rows: &[Vec<String>], width: usize) -> Vec<Vec<String>> {
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0).max(width);
+    rows.iter()
+        .map(|row| {
+            let mut row = row.clone();
+            row.resize(width, String::new());
+            row
+        })
+        .collect()
+}
+
+/// Renders a `DataMatrix` into the bytes of a Glotaran `.ascii` file, without
+/// touching the filesystem. `display_name` is the free-form title Glotaran
+/// expects on the first line (usually the eventual output filename).
+fn render(matrix: &DataMatrix, display_name: &str, author: &str, format: OutputFormat) -> anyhow::Result<Vec<u8>> {
+    let mut buffer: Vec<u8> = Vec::new();
+    writeln!(buffer, "{display_name}")?;
+    writeln!(buffer, "{author}")?;
+
+    let width = matrix.wavelengths.len() + 1;
+    let (format_label, intervalnr, header_row, body_rows) = match format {
+        OutputFormat::WavelengthExplicit => {
+            let rows = pad_rows(&matrix.rows, width);
+            // Rows may have been padded wider than the header (a body row
+            // wider than its header's wavelength count); widen the header to
+            // match so the non-flexible tsv writer doesn't reject them as
+            // unequal-length records.
+            let row_width = rows.first().map_or(width, Vec::len);
+            let mut header_row = vec![String::new()];
+            header_row.extend(matrix.wavelengths.iter().cloned());
+            header_row.resize(row_width, String::new());
+            ("wavelength explicit", matrix.wavelengths.len(), header_row, rows)
+        }
+        OutputFormat::TimeExplicit => {
+            let rows = pad_rows(&matrix.rows, width);
+            let times = rows.iter().map(|row| row[0].clone()).collect::<Vec<_>>();
+            let mut header_row = vec![String::new()];
+            header_row.extend(times);
+            let body_rows = matrix
+                .wavelengths
+                .iter()
+                .enumerate()
+                .map(|(wi, wavelength)| {
+                    let mut row = vec![wavelength.clone()];
+                    row.extend(rows.iter().map(|data_row| data_row[wi + 1].clone()));
+                    row
+                })
+                .collect::<Vec<_>>();
+            ("time explicit", rows.len(), header_row, body_rows)
+        }
+    };
+    writeln!(buffer, "{format_label}")?;
+    writeln!(buffer, "intervalnr {intervalnr}")?;
+
+    let mut writer = csv::WriterBuilder::new().delimiter(b'\t').from_writer(buffer);
+    writer.write_record(&header_row)?;
+    for row in &body_rows {
+        writer.write_record(row)?;
+    }
     writer.flush()?;
-    anyhow::Ok(filename.into())
+    anyhow::Ok(writer.into_inner()?)
+}
+
+fn write_to_file(matrix: DataMatrix, sink: &Sink, author: &str, format: OutputFormat) -> anyhow::Result<()> {
+    // eprintln!, not println!, so stdout stays clean for piping the ascii itself.
+    eprintln!("Contents converted written to {}", sink.describe());
+    let ascii = render(&matrix, &sink.label(), author, format)?;
+    sink.write_all(&ascii)?;
+    anyhow::Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A body row wider than the header (the ragged LFP case) must still
+    /// render: the header grows to match instead of the writer rejecting the
+    /// row as unequal-length, and the extra cell isn't dropped.
+    #[test]
+    fn render_wavelength_explicit_keeps_rows_wider_than_header() {
+        let matrix = DataMatrix {
+            wavelengths: vec!["450".to_owned()],
+            rows: vec![vec!["0".to_owned(), "1.0".to_owned(), "2.0".to_owned()]],
+        };
+        let ascii = render(&matrix, "wide.ascii", "Tester", OutputFormat::WavelengthExplicit)
+            .expect("wider-than-header row should still render");
+        let body_line = String::from_utf8(ascii).unwrap().lines().last().unwrap().to_owned();
+        assert_eq!(body_line, "0\t1.0\t2.0");
+    }
 }